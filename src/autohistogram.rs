@@ -0,0 +1,379 @@
+//! An auto-ranging, high-dynamic-range histogram.
+//!
+//! Unlike the histograms produced by [`define_histogram!`](macro.define_histogram.html),
+//! `AutoHistogram` does not require the number of bins or the value range to be
+//! known ahead of time. It is modeled on the bucketing scheme used by
+//! [HdrHistogram](http://hdrhistogram.org/): the value domain is split into
+//! power-of-two "buckets", and within each bucket a fixed number of linearly
+//! spaced "sub-buckets" bounds the relative error to `10^-significant_figures`
+//! across the whole range.
+
+/// An auto-ranging, high-dynamic-range histogram for `u64` samples.
+///
+/// The histogram is configured with a lowest discernible value, a highest
+/// trackable value and a number of significant decimal figures (1 to 5). It
+/// grows on demand (doubling its top bucket) when a recorded value exceeds
+/// the range covered so far, up to the configured highest trackable value.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AutoHistogram {
+    lowest_discernible_value: u64,
+    highest_trackable_value: u64,
+    significant_figures: u8,
+
+    unit_magnitude: u32,
+    sub_bucket_half_count_magnitude: u32,
+    sub_bucket_count: u32,
+    sub_bucket_half_count: u32,
+    sub_bucket_mask: u64,
+    bucket_count: u32,
+
+    counts: Vec<u64>,
+
+    total_count: u64,
+    min: u64,
+    max: u64,
+}
+
+#[inline]
+fn log2_floor(value: u64) -> u32 {
+    63 - value.leading_zeros()
+}
+
+impl AutoHistogram {
+    /// Construct a new auto-ranging histogram.
+    ///
+    /// `lowest_discernible_value` must be at least `1`, `highest_trackable_value`
+    /// must be at least `2 * lowest_discernible_value`, and `significant_figures`
+    /// must be between `1` and `5` inclusive.
+    pub fn new(lowest_discernible_value: u64, highest_trackable_value: u64, significant_figures: u8) -> Self {
+        assert!(lowest_discernible_value >= 1);
+        assert!(highest_trackable_value >= 2 * lowest_discernible_value);
+        assert!((1..=5).contains(&significant_figures));
+
+        let unit_magnitude = log2_floor(lowest_discernible_value);
+
+        let largest_value_with_single_unit_resolution = 2 * 10u64.pow(significant_figures as u32);
+        let sub_bucket_count_magnitude = (largest_value_with_single_unit_resolution as f64).log2().ceil() as u32;
+        let sub_bucket_half_count_magnitude = sub_bucket_count_magnitude.saturating_sub(1);
+        let sub_bucket_count = 1u32 << (sub_bucket_half_count_magnitude + 1);
+        let sub_bucket_half_count = sub_bucket_count / 2;
+        let sub_bucket_mask = ((sub_bucket_count as u64) - 1) << unit_magnitude;
+
+        let mut histogram = Self {
+            lowest_discernible_value,
+            highest_trackable_value,
+            significant_figures,
+            unit_magnitude,
+            sub_bucket_half_count_magnitude,
+            sub_bucket_count,
+            sub_bucket_half_count,
+            sub_bucket_mask,
+            bucket_count: 1,
+            counts: Vec::new(),
+            total_count: 0,
+            min: u64::MAX,
+            max: 0,
+        };
+        // Start out sized for a single bucket (covering values up to roughly
+        // `lowest_discernible_value`'s magnitude) and let `grow_to_accommodate`
+        // extend the counts array on demand as larger values are recorded, up
+        // to `highest_trackable_value`.
+        let len = histogram.counts_array_length(histogram.bucket_count);
+        histogram.counts = vec![0; len];
+        histogram
+    }
+
+    fn buckets_needed_to_cover(&self, value: u64) -> u32 {
+        let mut smallest_untrackable_value = (self.sub_bucket_count as u64) << self.unit_magnitude;
+        let mut buckets_needed = 1;
+        while smallest_untrackable_value <= value {
+            if smallest_untrackable_value > u64::MAX / 2 {
+                return buckets_needed + 1;
+            }
+            smallest_untrackable_value <<= 1;
+            buckets_needed += 1;
+        }
+        buckets_needed
+    }
+
+    fn counts_array_length(&self, bucket_count: u32) -> usize {
+        ((bucket_count + 1) * self.sub_bucket_half_count) as usize
+    }
+
+    fn bucket_index(&self, value: u64) -> u32 {
+        let pow2ceiling = 64 - (value | self.sub_bucket_mask).leading_zeros();
+        pow2ceiling.saturating_sub(self.unit_magnitude + self.sub_bucket_half_count_magnitude + 1)
+    }
+
+    fn sub_bucket_index(&self, value: u64, bucket_index: u32) -> u32 {
+        (value >> (bucket_index as u64 + self.unit_magnitude as u64)) as u32
+    }
+
+    fn counts_index(&self, bucket_index: u32, sub_bucket_index: u32) -> usize {
+        // For `bucket_index == 0` the sub-bucket index ranges over the whole
+        // `0..sub_bucket_count`, so `offset_in_bucket` can be negative there;
+        // do the subtraction in a signed type, as the reference HdrHistogram
+        // implementation does.
+        let bucket_base_index = (bucket_index as i64 + 1) << self.sub_bucket_half_count_magnitude;
+        let offset_in_bucket = sub_bucket_index as i64 - self.sub_bucket_half_count as i64;
+        (bucket_base_index + offset_in_bucket) as usize
+    }
+
+    fn index_for(&self, value: u64) -> usize {
+        let bucket_index = self.bucket_index(value);
+        let sub_bucket_index = self.sub_bucket_index(value, bucket_index);
+        self.counts_index(bucket_index, sub_bucket_index)
+    }
+
+    /// Grow the histogram so that `value` is representable, up to
+    /// `highest_trackable_value`.
+    fn grow_to_accommodate(&mut self, value: u64) {
+        let new_bucket_count = self.buckets_needed_to_cover(value);
+        if new_bucket_count <= self.bucket_count {
+            return;
+        }
+        let new_len = self.counts_array_length(new_bucket_count);
+        self.counts.resize(new_len, 0);
+        self.bucket_count = new_bucket_count;
+    }
+
+    /// Record a single occurrence of `value`.
+    ///
+    /// Values larger than the current range are accommodated by growing the
+    /// histogram, as long as they do not exceed `highest_trackable_value`.
+    pub fn record(&mut self, value: u64) {
+        self.record_n(value, 1);
+    }
+
+    /// Record `count` occurrences of `value`.
+    pub fn record_n(&mut self, value: u64, count: u64) {
+        let value = value.min(self.highest_trackable_value);
+        self.grow_to_accommodate(value);
+        let index = self.index_for(value);
+        self.counts[index] += count;
+        self.total_count += count;
+        if value < self.min {
+            self.min = value;
+        }
+        if value > self.max {
+            self.max = value;
+        }
+    }
+
+    /// Return the smallest recorded value, or `None` if the histogram is empty.
+    pub fn min(&self) -> Option<u64> {
+        if self.total_count == 0 {
+            None
+        } else {
+            Some(self.min)
+        }
+    }
+
+    /// Return the largest recorded value, or `None` if the histogram is empty.
+    pub fn max(&self) -> Option<u64> {
+        if self.total_count == 0 {
+            None
+        } else {
+            Some(self.max)
+        }
+    }
+
+    /// Return the total number of recorded samples.
+    pub fn total_count(&self) -> u64 {
+        self.total_count
+    }
+
+    /// Merge another histogram into this one.
+    ///
+    /// If the two histograms were not configured identically, the other
+    /// histogram's samples are re-binned into this one's configuration
+    /// rather than being rejected.
+    pub fn merge(&mut self, other: &Self) {
+        if self.lowest_discernible_value == other.lowest_discernible_value
+            && self.significant_figures == other.significant_figures
+            && self.unit_magnitude == other.unit_magnitude
+            && self.sub_bucket_half_count_magnitude == other.sub_bucket_half_count_magnitude
+        {
+            for bucket_index in 0..other.bucket_count {
+                let sub_bucket_start = if bucket_index == 0 {
+                    0
+                } else {
+                    other.sub_bucket_half_count
+                };
+                for sub_bucket_index in sub_bucket_start..other.sub_bucket_count {
+                    let idx = other.counts_index(bucket_index, sub_bucket_index);
+                    let count = other.counts[idx];
+                    if count == 0 {
+                        continue;
+                    }
+                    let value = (sub_bucket_index as u64) << (bucket_index as u64 + other.unit_magnitude as u64);
+                    self.record_n(value, count);
+                }
+            }
+        } else {
+            for ((lower, _upper), count) in other.iter() {
+                if count == 0 {
+                    continue;
+                }
+                self.record_n(lower, count);
+            }
+        }
+    }
+
+    /// Iterate over all `(range, count)` pairs, where `range` is
+    /// `(lower, upper)` of the bin. Empty bins are included, with a count
+    /// of `0`.
+    pub fn iter(&self) -> IterAutoHistogram<'_> {
+        IterAutoHistogram {
+            histogram: self,
+            bucket_index: 0,
+            // Bucket 0 has no bucket below it to alias against, so unlike
+            // every other bucket it covers sub-buckets `0..sub_bucket_count`
+            // rather than just the upper half.
+            sub_bucket_index: 0,
+        }
+    }
+
+    /// Estimate the value below which `q` (between `0.` and `1.`) of the
+    /// recorded samples fall.
+    pub fn value_at_quantile(&self, q: f64) -> f64 {
+        if self.total_count == 0 {
+            return 0.;
+        }
+        let target = q * (self.total_count as f64);
+        let mut cumulative = 0u64;
+        for ((lower, upper), count) in self.iter() {
+            let next_cumulative = cumulative + count;
+            if count > 0 && (next_cumulative as f64) >= target {
+                let frac = (target - cumulative as f64) / (count as f64);
+                return (lower as f64) + frac * ((upper - lower) as f64);
+            }
+            cumulative = next_cumulative;
+        }
+        self.max as f64
+    }
+
+    /// Estimate the fraction of recorded samples that are strictly below
+    /// `value`.
+    pub fn quantile_below(&self, value: u64) -> f64 {
+        if self.total_count == 0 {
+            return 0.;
+        }
+        let mut cumulative = 0u64;
+        for ((lower, upper), count) in self.iter() {
+            if value < lower {
+                break;
+            }
+            if value < upper {
+                let frac = (value - lower) as f64 / (upper - lower) as f64;
+                cumulative += (frac * (count as f64)) as u64;
+                break;
+            }
+            cumulative += count;
+        }
+        (cumulative as f64) / (self.total_count as f64)
+    }
+
+    /// Estimate the median of the recorded samples.
+    #[inline]
+    pub fn median(&self) -> f64 {
+        self.value_at_quantile(0.5)
+    }
+}
+
+/// Iterate over all `(range, count)` pairs of an [`AutoHistogram`](struct.AutoHistogram.html).
+pub struct IterAutoHistogram<'a> {
+    histogram: &'a AutoHistogram,
+    bucket_index: u32,
+    sub_bucket_index: u32,
+}
+
+impl<'a> Iterator for IterAutoHistogram<'a> {
+    type Item = ((u64, u64), u64);
+
+    fn next(&mut self) -> Option<((u64, u64), u64)> {
+        loop {
+            if self.bucket_index >= self.histogram.bucket_count {
+                return None;
+            }
+            if self.sub_bucket_index >= self.histogram.sub_bucket_count {
+                self.bucket_index += 1;
+                self.sub_bucket_index = if self.bucket_index == 0 {
+                    0
+                } else {
+                    self.histogram.sub_bucket_half_count
+                };
+                continue;
+            }
+            let bucket_index = self.bucket_index;
+            let sub_bucket_index = self.sub_bucket_index;
+            self.sub_bucket_index += 1;
+
+            let shift = bucket_index as u64 + self.histogram.unit_magnitude as u64;
+            let lower = (sub_bucket_index as u64) << shift;
+            let upper = ((sub_bucket_index as u64) + 1) << shift;
+            let idx = self.histogram.counts_index(bucket_index, sub_bucket_index);
+            let count = self.histogram.counts[idx];
+            return Some(((lower, upper), count));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AutoHistogram;
+
+    #[test]
+    fn records_and_reports_min_max() {
+        let mut h = AutoHistogram::new(1, 3_600_000_000, 3);
+        h.record(1);
+        h.record(1000);
+        h.record(1_000_000);
+        assert_eq!(h.min(), Some(1));
+        assert_eq!(h.max(), Some(1_000_000));
+        assert_eq!(h.total_count(), 3);
+    }
+
+    #[test]
+    fn grows_when_value_exceeds_current_range() {
+        let mut h = AutoHistogram::new(1, 3_600_000_000, 3);
+        let initial_bucket_count = h.bucket_count;
+        h.record(2_000_000_000);
+        assert!(h.bucket_count > initial_bucket_count);
+        assert_eq!(h.max(), Some(2_000_000_000));
+    }
+
+    #[test]
+    fn value_at_quantile_interpolates() {
+        let mut h = AutoHistogram::new(1, 1_000_000, 3);
+        for i in 1..=100 {
+            h.record(i);
+        }
+        let median = h.median();
+        assert!((49. ..=51.).contains(&median), "median = {}", median);
+    }
+
+    #[test]
+    fn merge_accumulates_counts() {
+        let mut a = AutoHistogram::new(1, 1_000_000, 3);
+        let mut b = AutoHistogram::new(1, 1_000_000, 3);
+        a.record(100);
+        b.record(100);
+        b.record(200);
+        a.merge(&b);
+        assert_eq!(a.total_count(), 3);
+        assert_eq!(a.min(), Some(100));
+        assert_eq!(a.max(), Some(200));
+    }
+
+    #[test]
+    fn merge_clamps_to_its_own_ceiling_when_the_other_ceiling_is_higher() {
+        let mut small = AutoHistogram::new(1, 1_000, 3);
+        let mut big = AutoHistogram::new(1, 10_000_000, 3);
+        big.record(5_000_000);
+        small.merge(&big);
+        assert_eq!(small.total_count(), 1);
+        assert_eq!(small.max(), Some(1_000));
+    }
+}
@@ -1,5 +1,222 @@
+/// Shared behavior of histograms with `u64` bin counts.
+///
+/// Implemented by every type generated by
+/// [`define_histogram!`](macro.define_histogram.html). Quantile estimation
+/// lives here (as provided methods built on `bins`/`ranges`) rather than as
+/// inherent methods on each generated type, so generic code —
+/// `fn f<H: Histogram>(h: &H)`, or a `&dyn Histogram` — can query any
+/// histogram without depending on its concrete type.
+pub trait Histogram {
+    /// Return the bins of the histogram.
+    fn bins(&self) -> &[u64];
+
+    /// Return the ranges of the histogram: `bins().len() + 1` sorted
+    /// boundaries, where bin `i` covers `[ranges()[i], ranges()[i + 1])`.
+    fn ranges(&self) -> &[f64];
+
+    /// Return the number of samples that fell below the histogram's
+    /// range. Always `0` unless the histogram saturates out-of-range
+    /// samples.
+    #[inline]
+    fn underflow(&self) -> u64 {
+        0
+    }
+
+    /// Return the number of samples that fell at or above the
+    /// histogram's range. Always `0` unless the histogram saturates
+    /// out-of-range samples.
+    #[inline]
+    fn overflow(&self) -> u64 {
+        0
+    }
+
+    /// Return the total number of samples recorded in the histogram,
+    /// including those in the underflow and overflow counters.
+    #[inline]
+    fn total_count(&self) -> u64 {
+        self.bins().iter().sum::<u64>() + self.underflow() + self.overflow()
+    }
+
+    /// Estimate the value below which `q` (between `0.` and `1.`) of the
+    /// recorded samples fall.
+    ///
+    /// `total_count` includes the underflow/overflow counters, so the
+    /// running total starts at `underflow` before the bins are walked; a
+    /// `target` that falls inside the underflow mass returns the lowest
+    /// range boundary, and one that runs past the last bin (i.e. into the
+    /// overflow mass) returns the highest.
+    fn value_at_quantile(&self, q: f64) -> f64 {
+        let ranges = self.ranges();
+        let range_min = ranges[0];
+        let range_max = ranges[ranges.len() - 1];
+        let total = self.total_count();
+        if total == 0 {
+            return range_min;
+        }
+        let target = q * (total as f64);
+        if target <= self.underflow() as f64 {
+            return range_min;
+        }
+        let mut cumulative = self.underflow();
+        for (i, &count) in self.bins().iter().enumerate() {
+            let (lower, upper) = (ranges[i], ranges[i + 1]);
+            let next_cumulative = cumulative + count;
+            if count > 0 && (next_cumulative as f64) >= target {
+                let frac = (target - cumulative as f64) / (count as f64);
+                return lower + frac * (upper - lower);
+            }
+            cumulative = next_cumulative;
+        }
+        range_max
+    }
+
+    /// Estimate the fraction of recorded samples that are strictly below
+    /// `value`.
+    ///
+    /// The underflow counter is entirely below the lowest range boundary,
+    /// so it is counted as below `value` for any `value` at or above it.
+    fn quantile_below(&self, value: f64) -> f64 {
+        let ranges = self.ranges();
+        let total = self.total_count();
+        if total == 0 {
+            return 0.;
+        }
+        let mut cumulative = self.underflow();
+        for (i, &count) in self.bins().iter().enumerate() {
+            let (lower, upper) = (ranges[i], ranges[i + 1]);
+            if value < lower {
+                break;
+            }
+            if value < upper {
+                let frac = (value - lower) / (upper - lower);
+                cumulative += (frac * (count as f64)) as u64;
+                break;
+            }
+            cumulative += count;
+        }
+        (cumulative as f64) / (total as f64)
+    }
+
+    /// Estimate the median of the recorded samples.
+    #[inline]
+    fn median(&self) -> f64 {
+        self.value_at_quantile(0.5)
+    }
+}
+
+/// Shared behavior of histograms with floating-point (weighted) bin
+/// counts.
+///
+/// Mirrors [`Histogram`](trait.Histogram.html) for the types generated by
+/// [`define_histogram_weighted!`](macro.define_histogram_weighted.html).
+/// It's a separate trait rather than a `Histogram` with a generic count
+/// type because the two count representations round and compare
+/// differently, and only one macro-generated type implements each.
+pub trait HistogramWeighted {
+    /// Return the bins of the histogram.
+    fn bins(&self) -> &[f64];
+
+    /// Return the ranges of the histogram: `bins().len() + 1` sorted
+    /// boundaries, where bin `i` covers `[ranges()[i], ranges()[i + 1])`.
+    fn ranges(&self) -> &[f64];
+
+    /// Return the weight of samples that fell below the histogram's
+    /// range. Always `0.` unless the histogram saturates out-of-range
+    /// samples.
+    #[inline]
+    fn underflow(&self) -> f64 {
+        0.
+    }
+
+    /// Return the weight of samples that fell at or above the
+    /// histogram's range. Always `0.` unless the histogram saturates
+    /// out-of-range samples.
+    #[inline]
+    fn overflow(&self) -> f64 {
+        0.
+    }
+
+    /// Return the total weight of samples recorded in the histogram,
+    /// including those in the underflow and overflow counters.
+    #[inline]
+    fn total_count(&self) -> f64 {
+        self.bins().iter().sum::<f64>() + self.underflow() + self.overflow()
+    }
+
+    /// Estimate the value below which `q` (between `0.` and `1.`) of the
+    /// recorded weight falls.
+    ///
+    /// `total_count` includes the underflow/overflow counters, so the
+    /// running total starts at `underflow` before the bins are walked; a
+    /// `target` that falls inside the underflow mass returns the lowest
+    /// range boundary, and one that runs past the last bin (i.e. into the
+    /// overflow mass) returns the highest.
+    fn value_at_quantile(&self, q: f64) -> f64 {
+        let ranges = self.ranges();
+        let range_min = ranges[0];
+        let range_max = ranges[ranges.len() - 1];
+        let total = self.total_count();
+        if total == 0. {
+            return range_min;
+        }
+        let target = q * total;
+        if target <= self.underflow() {
+            return range_min;
+        }
+        let mut cumulative = self.underflow();
+        for (i, &count) in self.bins().iter().enumerate() {
+            let (lower, upper) = (ranges[i], ranges[i + 1]);
+            let next_cumulative = cumulative + count;
+            if count > 0. && next_cumulative >= target {
+                let frac = (target - cumulative) / count;
+                return lower + frac * (upper - lower);
+            }
+            cumulative = next_cumulative;
+        }
+        range_max
+    }
+
+    /// Estimate the fraction of recorded weight that falls strictly below
+    /// `value`.
+    ///
+    /// The underflow counter is entirely below the lowest range boundary,
+    /// so it is counted as below `value` for any `value` at or above it.
+    fn quantile_below(&self, value: f64) -> f64 {
+        let ranges = self.ranges();
+        let total = self.total_count();
+        if total == 0. {
+            return 0.;
+        }
+        let mut cumulative = self.underflow();
+        for (i, &count) in self.bins().iter().enumerate() {
+            let (lower, upper) = (ranges[i], ranges[i + 1]);
+            if value < lower {
+                break;
+            }
+            if value < upper {
+                let frac = (value - lower) / (upper - lower);
+                cumulative += frac * count;
+                break;
+            }
+            cumulative += count;
+        }
+        cumulative / total
+    }
+
+    /// Estimate the median of the recorded samples.
+    #[inline]
+    fn median(&self) -> f64 {
+        self.value_at_quantile(0.5)
+    }
+}
+
 /// Define a histogram with a number of bins known at compile time.
 ///
+/// With the `serde` feature enabled, the generated type derives
+/// `Serialize`/`Deserialize` so it can be persisted or sent over the wire.
+/// Extending this to `Min`/`Max` and the crate's other estimators is
+/// tracked separately as `alexanderkjall/average#chunk0-7`.
+///
 /// ```
 /// # extern crate core;
 /// # #[macro_use] extern crate average;
@@ -22,9 +239,13 @@ macro_rules! define_histogram {
 
         /// A histogram with a number of bins known at compile time.
         #[derive(Debug, Clone)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
         pub struct $name {
             range: [f64; LEN + 1],
             bin: [u64; LEN],
+            underflow: u64,
+            overflow: u64,
+            saturate: bool,
         }
 
         impl $name {
@@ -40,9 +261,23 @@ macro_rules! define_histogram {
                 Self {
                     range,
                     bin: [0; LEN],
+                    underflow: 0,
+                    overflow: 0,
+                    saturate: false,
                 }
             }
 
+            /// Construct a histogram with constant bin width where samples
+            /// outside `[start, end)` are counted in
+            /// [`underflow`](#method.underflow) / [`overflow`](#method.overflow)
+            /// instead of being rejected by [`add`](#method.add).
+            #[inline]
+            pub fn with_const_width_saturating(start: f64, end: f64) -> Self {
+                let mut histogram = Self::with_const_width(start, end);
+                histogram.saturate = true;
+                histogram
+            }
+
             /// Construct a histogram from given ranges.
             ///
             /// The ranges are given by an iterator of floats where neighboring
@@ -76,6 +311,9 @@ macro_rules! define_histogram {
                 Ok(Self {
                     range,
                     bin: [0; LEN],
+                    underflow: 0,
+                    overflow: 0,
+                    saturate: false,
                 })
             }
 
@@ -101,17 +339,48 @@ macro_rules! define_histogram {
 
             /// Add a sample to the histogram.
             ///
-            /// Fails if the sample is out of range of the histogram.
+            /// Fails if the sample is out of range of the histogram, unless
+            /// the histogram was constructed with
+            /// [`with_const_width_saturating`](#method.with_const_width_saturating),
+            /// in which case out-of-range samples are counted in
+            /// [`underflow`](#method.underflow) / [`overflow`](#method.overflow).
             #[inline]
             pub fn add(&mut self, x: f64) -> Result<(), ()> {
-                if let Ok(i) = self.find(x) {
-                    self.bin[i] += 1;
-                    Ok(())
-                } else {
-                    Err(())
+                match self.find(x) {
+                    Ok(i) => {
+                        self.bin[i] += 1;
+                        Ok(())
+                    },
+                    Err(()) if self.saturate => {
+                        if x < self.range_min() {
+                            self.underflow += 1;
+                        } else {
+                            self.overflow += 1;
+                        }
+                        Ok(())
+                    },
+                    Err(()) => Err(()),
                 }
             }
 
+            /// Return the number of samples that fell below the histogram's
+            /// range. Always `0` unless the histogram saturates out-of-range
+            /// samples (see
+            /// [`with_const_width_saturating`](#method.with_const_width_saturating)).
+            #[inline]
+            pub fn underflow(&self) -> u64 {
+                self.underflow
+            }
+
+            /// Return the number of samples that fell at or above the
+            /// histogram's range. Always `0` unless the histogram saturates
+            /// out-of-range samples (see
+            /// [`with_const_width_saturating`](#method.with_const_width_saturating)).
+            #[inline]
+            pub fn overflow(&self) -> u64 {
+                self.overflow
+            }
+
             /// Return the ranges of the histogram.
             #[inline]
             pub fn ranges(&self) -> &[f64] {
@@ -129,6 +398,8 @@ macro_rules! define_histogram {
             #[inline]
             pub fn reset(&mut self) {
                 self.bin = [0; LEN];
+                self.underflow = 0;
+                self.overflow = 0;
             }
 
             /// Return the lower range limit.
@@ -146,6 +417,37 @@ macro_rules! define_histogram {
             pub fn range_max(&self) -> f64 {
                 self.range[LEN]
             }
+
+            /// Return the total number of samples recorded in the histogram,
+            /// including those in the underflow and overflow counters.
+            #[inline]
+            pub fn total_count(&self) -> u64 {
+                self.bin.iter().sum::<u64>() + self.underflow + self.overflow
+            }
+
+            /// Estimate the value below which `q` (between `0.` and `1.`) of the
+            /// recorded samples fall.
+            ///
+            /// See [`Histogram::value_at_quantile`](trait.Histogram.html#method.value_at_quantile).
+            #[inline]
+            pub fn value_at_quantile(&self, q: f64) -> f64 {
+                $crate::Histogram::value_at_quantile(self, q)
+            }
+
+            /// Estimate the fraction of recorded samples that are strictly
+            /// below `value`.
+            ///
+            /// See [`Histogram::quantile_below`](trait.Histogram.html#method.quantile_below).
+            #[inline]
+            pub fn quantile_below(&self, value: f64) -> f64 {
+                $crate::Histogram::quantile_below(self, value)
+            }
+
+            /// Estimate the median of the recorded samples.
+            #[inline]
+            pub fn median(&self) -> f64 {
+                $crate::Histogram::median(self)
+            }
         }
 
         /// Iterate over all `(range, count)` pairs in the histogram.
@@ -184,6 +486,21 @@ macro_rules! define_histogram {
             fn bins(&self) -> &[u64] {
                 &self.bin as &[u64]
             }
+
+            #[inline]
+            fn ranges(&self) -> &[f64] {
+                &self.range as &[f64]
+            }
+
+            #[inline]
+            fn underflow(&self) -> u64 {
+                self.underflow
+            }
+
+            #[inline]
+            fn overflow(&self) -> u64 {
+                self.overflow
+            }
         }
 
         impl<'a> ::core::ops::AddAssign<&'a Self> for $name {
@@ -193,16 +510,592 @@ macro_rules! define_histogram {
                 for (x, y) in self.bin.iter_mut().zip(other.bin.iter()) {
                     *x += y;
                 }
+                self.underflow += other.underflow;
+                self.overflow += other.overflow;
             }
         }
 
         impl ::core::ops::MulAssign<u64> for $name {
             #[inline]
             fn mul_assign(&mut self, other: u64) {
+                self.underflow *= other;
+                self.overflow *= other;
                 for x in self.bin.iter_mut() {
                     *x *= other;
                 }
             }
         }
+
+        impl ::core::fmt::Display for $name {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                writeln!(f, "total samples = {}", self.total_count())?;
+                writeln!(f, "underflow = {}", self.underflow())?;
+                writeln!(f, "overflow = {}", self.overflow())?;
+
+                let bin_total: u64 = self.bin.iter().sum();
+                if bin_total == 0 {
+                    return Ok(());
+                }
+
+                let mut mean = 0.;
+                let mut max_count = 0;
+                for ((lower, upper), count) in self.iter() {
+                    let mid = 0.5 * (lower + upper);
+                    mean += mid * (count as f64);
+                    if count > max_count {
+                        max_count = count;
+                    }
+                }
+                mean /= bin_total as f64;
+
+                let mut variance = 0.;
+                for ((lower, upper), count) in self.iter() {
+                    let mid = 0.5 * (lower + upper);
+                    variance += (count as f64) * (mid - mean) * (mid - mean);
+                }
+                variance /= bin_total as f64;
+
+                writeln!(f, "min = {}", self.range_min())?;
+                writeln!(f, "max = {}", self.range_max())?;
+                writeln!(f, "mean = {}", mean)?;
+                writeln!(f, "standard deviation = {}", variance.sqrt())?;
+                writeln!(f, "variance = {}", variance)?;
+                writeln!(f)?;
+
+                const MAX_BAR_WIDTH: u64 = 50;
+                for ((lower, upper), count) in self.iter() {
+                    let bar_width = if max_count == 0 {
+                        0
+                    } else {
+                        count * MAX_BAR_WIDTH / max_count
+                    };
+                    writeln!(
+                        f,
+                        "[{:>12.4}, {:>12.4}) {:>8} {}",
+                        lower,
+                        upper,
+                        count,
+                        "#".repeat(bar_width as usize),
+                    )?;
+                }
+                Ok(())
+            }
+        }
     );
 }
+
+/// Define a histogram with a number of bins known at compile time, whose
+/// bins hold floating-point counts instead of `u64` counts.
+///
+/// This allows accumulating fractional or weighted observations, e.g.
+/// importance-sampled Monte Carlo data, or histograms scaled by a factor.
+///
+/// With the `serde` feature enabled, the generated type derives
+/// `Serialize`/`Deserialize` so it can be persisted or sent over the wire.
+/// Extending this to `Min`/`Max` and the crate's other estimators is
+/// tracked separately as `alexanderkjall/average#chunk0-7`.
+///
+/// ```
+/// # extern crate core;
+/// # #[macro_use] extern crate average;
+/// # fn main() {
+/// define_histogram_weighted!(Histogram10, 10);
+/// let mut h = Histogram10::with_const_width(0., 100.);
+/// for i in 0..100 {
+///     h.add_weighted(i as f64, 0.5).unwrap();
+/// }
+/// assert_eq!(h.bins(), &[5., 5., 5., 5., 5., 5., 5., 5., 5., 5.]);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! define_histogram_weighted {
+    ($name:ident, $LEN:expr) => (
+        /// The number of bins of the histogram.
+        const LEN: usize = $LEN;
+
+        /// A histogram with floating-point bin counts and a number of bins
+        /// known at compile time.
+        #[derive(Debug, Clone)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        pub struct $name {
+            range: [f64; LEN + 1],
+            bin: [f64; LEN],
+            underflow: f64,
+            overflow: f64,
+            saturate: bool,
+        }
+
+        impl $name {
+            /// Construct a histogram with constant bin width.
+            #[inline]
+            pub fn with_const_width(start: f64, end: f64) -> Self {
+                let step = (end - start) / (LEN as f64);
+                let mut range = [0.; LEN + 1];
+                for (i, r) in range.iter_mut().enumerate() {
+                    *r = start + step * (i as f64);
+                }
+
+                Self {
+                    range,
+                    bin: [0.; LEN],
+                    underflow: 0.,
+                    overflow: 0.,
+                    saturate: false,
+                }
+            }
+
+            /// Construct a histogram with constant bin width where samples
+            /// outside `[start, end)` are counted in
+            /// [`underflow`](#method.underflow) / [`overflow`](#method.overflow)
+            /// instead of being rejected by [`add`](#method.add) /
+            /// [`add_weighted`](#method.add_weighted).
+            #[inline]
+            pub fn with_const_width_saturating(start: f64, end: f64) -> Self {
+                let mut histogram = Self::with_const_width(start, end);
+                histogram.saturate = true;
+                histogram
+            }
+
+            /// Construct a histogram from given ranges.
+            ///
+            /// The ranges are given by an iterator of floats where neighboring
+            /// pairs `(a, b)` define a bin for all `x` where `a <= x < b`.
+            ///
+            /// Fails if the iterator is too short (less than `n + 1` where `n`
+            /// is the number of bins), is not sorted or contains `nan`. `inf`
+            /// and empty ranges are allowed.
+            #[inline]
+            pub fn from_ranges<T>(ranges: T) -> Result<Self, ()>
+                where T: IntoIterator<Item = f64>
+            {
+                let mut range = [0.; LEN + 1];
+                let mut last_i = 0;
+                for (i, r) in ranges.into_iter().enumerate() {
+                    if i > LEN {
+                        break;
+                    }
+                    if r.is_nan() {
+                        return Err(());
+                    }
+                    if i > 0 && range[i - 1] > r {
+                        return Err(());
+                    }
+                    range[i] = r;
+                    last_i = i;
+                }
+                if last_i != LEN {
+                    return Err(());
+                }
+                Ok(Self {
+                    range,
+                    bin: [0.; LEN],
+                    underflow: 0.,
+                    overflow: 0.,
+                    saturate: false,
+                })
+            }
+
+            /// Find the index of the bin corresponding to the given sample.
+            ///
+            /// Fails if the sample is out of range of the histogram.
+            #[inline]
+            pub fn find(&self, x: f64) -> Result<usize, ()> {
+                // We made sure our ranges are valid at construction, so we can
+                // safely unwrap.
+                match self.range.binary_search_by(|p| p.partial_cmp(&x).unwrap()) {
+                    Ok(i) if i < LEN => {
+                        Ok(i)
+                    },
+                    Err(i) if i > 0 && i < LEN + 1 => {
+                        Ok(i - 1)
+                    },
+                    _ => {
+                        Err(())
+                    },
+                }
+            }
+
+            /// Add a sample with weight `1.` to the histogram.
+            ///
+            /// Fails if the sample is out of range of the histogram, unless
+            /// the histogram was constructed with
+            /// [`with_const_width_saturating`](#method.with_const_width_saturating),
+            /// in which case out-of-range samples are counted in
+            /// [`underflow`](#method.underflow) / [`overflow`](#method.overflow).
+            #[inline]
+            pub fn add(&mut self, x: f64) -> Result<(), ()> {
+                self.add_weighted(x, 1.)
+            }
+
+            /// Add a sample with the given weight to the histogram.
+            ///
+            /// Fails if the sample is out of range of the histogram, unless
+            /// the histogram was constructed with
+            /// [`with_const_width_saturating`](#method.with_const_width_saturating),
+            /// in which case out-of-range samples are counted in
+            /// [`underflow`](#method.underflow) / [`overflow`](#method.overflow).
+            #[inline]
+            pub fn add_weighted(&mut self, x: f64, weight: f64) -> Result<(), ()> {
+                match self.find(x) {
+                    Ok(i) => {
+                        self.bin[i] += weight;
+                        Ok(())
+                    },
+                    Err(()) if self.saturate => {
+                        if x < self.range_min() {
+                            self.underflow += weight;
+                        } else {
+                            self.overflow += weight;
+                        }
+                        Ok(())
+                    },
+                    Err(()) => Err(()),
+                }
+            }
+
+            /// Return the bins of the histogram.
+            #[inline]
+            pub fn bins(&self) -> &[f64] {
+                &self.bin as &[f64]
+            }
+
+            /// Return the ranges of the histogram.
+            #[inline]
+            pub fn ranges(&self) -> &[f64] {
+                &self.range as &[f64]
+            }
+
+            /// Return an iterator over the bins and corresponding ranges:
+            /// `((lower, upper), count)`
+            #[inline]
+            pub fn iter(&self) -> IterHistogramWeighted {
+                self.into_iter()
+            }
+
+            /// Reset all bins to zero.
+            #[inline]
+            pub fn reset(&mut self) {
+                self.bin = [0.; LEN];
+                self.underflow = 0.;
+                self.overflow = 0.;
+            }
+
+            /// Return the lower range limit.
+            ///
+            /// (The corresponding bin might be empty.)
+            #[inline]
+            pub fn range_min(&self) -> f64 {
+                self.range[0]
+            }
+
+            /// Return the upper range limit.
+            ///
+            /// (The corresponding bin might be empty.)
+            #[inline]
+            pub fn range_max(&self) -> f64 {
+                self.range[LEN]
+            }
+
+            /// Return the total weight of samples recorded in the histogram,
+            /// including those in the underflow and overflow counters.
+            #[inline]
+            pub fn total_count(&self) -> f64 {
+                self.bin.iter().sum::<f64>() + self.underflow + self.overflow
+            }
+
+            /// Return the weight of samples that fell below the histogram's
+            /// range. Always `0.` unless the histogram saturates out-of-range
+            /// samples (see
+            /// [`with_const_width_saturating`](#method.with_const_width_saturating)).
+            #[inline]
+            pub fn underflow(&self) -> f64 {
+                self.underflow
+            }
+
+            /// Return the weight of samples that fell at or above the
+            /// histogram's range. Always `0.` unless the histogram saturates
+            /// out-of-range samples (see
+            /// [`with_const_width_saturating`](#method.with_const_width_saturating)).
+            #[inline]
+            pub fn overflow(&self) -> f64 {
+                self.overflow
+            }
+
+            /// Estimate the value below which `q` (between `0.` and `1.`) of the
+            /// recorded weight falls.
+            ///
+            /// See [`HistogramWeighted::value_at_quantile`](trait.HistogramWeighted.html#method.value_at_quantile).
+            #[inline]
+            pub fn value_at_quantile(&self, q: f64) -> f64 {
+                $crate::HistogramWeighted::value_at_quantile(self, q)
+            }
+
+            /// Estimate the fraction of recorded weight that falls strictly
+            /// below `value`.
+            ///
+            /// See [`HistogramWeighted::quantile_below`](trait.HistogramWeighted.html#method.quantile_below).
+            #[inline]
+            pub fn quantile_below(&self, value: f64) -> f64 {
+                $crate::HistogramWeighted::quantile_below(self, value)
+            }
+
+            /// Estimate the median of the recorded samples.
+            #[inline]
+            pub fn median(&self) -> f64 {
+                $crate::HistogramWeighted::median(self)
+            }
+        }
+
+        /// Iterate over all `(range, count)` pairs in the histogram.
+        pub struct IterHistogramWeighted<'a> {
+            remaining_bin: &'a [f64],
+            remaining_range: &'a [f64],
+        }
+
+        impl<'a> ::core::iter::Iterator for IterHistogramWeighted<'a> {
+            type Item = ((f64, f64), f64);
+            fn next(&mut self) -> Option<((f64, f64), f64)> {
+                if let Some((&bin, rest)) = self.remaining_bin.split_first() {
+                    let left = self.remaining_range[0];
+                    let right = self.remaining_range[1];
+                    self.remaining_bin = rest;
+                    self.remaining_range = &self.remaining_range[1..];
+                    return Some(((left, right), bin));
+                }
+                None
+            }
+        }
+
+        impl<'a> ::core::iter::IntoIterator for &'a $name {
+            type Item = ((f64, f64), f64);
+            type IntoIter = IterHistogramWeighted<'a>;
+            fn into_iter(self) -> IterHistogramWeighted<'a> {
+                IterHistogramWeighted {
+                    remaining_bin: self.bins(),
+                    remaining_range: self.ranges(),
+                }
+            }
+        }
+
+        impl $crate::HistogramWeighted for $name {
+            #[inline]
+            fn bins(&self) -> &[f64] {
+                &self.bin as &[f64]
+            }
+
+            #[inline]
+            fn ranges(&self) -> &[f64] {
+                &self.range as &[f64]
+            }
+
+            #[inline]
+            fn underflow(&self) -> f64 {
+                self.underflow
+            }
+
+            #[inline]
+            fn overflow(&self) -> f64 {
+                self.overflow
+            }
+        }
+
+        impl<'a> ::core::ops::AddAssign<&'a Self> for $name {
+            #[inline]
+            fn add_assign(&mut self, other: &Self) {
+                assert_eq!(self.range, other.range);
+                for (x, y) in self.bin.iter_mut().zip(other.bin.iter()) {
+                    *x += y;
+                }
+                self.underflow += other.underflow;
+                self.overflow += other.overflow;
+            }
+        }
+
+        impl ::core::ops::MulAssign<f64> for $name {
+            #[inline]
+            fn mul_assign(&mut self, other: f64) {
+                self.underflow *= other;
+                self.overflow *= other;
+                for x in self.bin.iter_mut() {
+                    *x *= other;
+                }
+            }
+        }
+
+        impl ::core::fmt::Display for $name {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                writeln!(f, "total samples = {}", self.total_count())?;
+                writeln!(f, "underflow = {}", self.underflow())?;
+                writeln!(f, "overflow = {}", self.overflow())?;
+
+                let bin_total: f64 = self.bin.iter().sum();
+                if bin_total == 0. {
+                    return Ok(());
+                }
+
+                let mut mean = 0.;
+                let mut max_count = 0.;
+                for ((lower, upper), count) in self.iter() {
+                    let mid = 0.5 * (lower + upper);
+                    mean += mid * count;
+                    if count > max_count {
+                        max_count = count;
+                    }
+                }
+                mean /= bin_total;
+
+                let mut variance = 0.;
+                for ((lower, upper), count) in self.iter() {
+                    let mid = 0.5 * (lower + upper);
+                    variance += count * (mid - mean) * (mid - mean);
+                }
+                variance /= bin_total;
+
+                writeln!(f, "min = {}", self.range_min())?;
+                writeln!(f, "max = {}", self.range_max())?;
+                writeln!(f, "mean = {}", mean)?;
+                writeln!(f, "standard deviation = {}", variance.sqrt())?;
+                writeln!(f, "variance = {}", variance)?;
+                writeln!(f)?;
+
+                const MAX_BAR_WIDTH: f64 = 50.;
+                for ((lower, upper), count) in self.iter() {
+                    let bar_width = if max_count == 0. {
+                        0.
+                    } else {
+                        count * MAX_BAR_WIDTH / max_count
+                    };
+                    writeln!(
+                        f,
+                        "[{:>12.4}, {:>12.4}) {:>8.4} {}",
+                        lower,
+                        upper,
+                        count,
+                        "#".repeat(bar_width as usize),
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    mod basic {
+        use crate::Histogram;
+
+        define_histogram!(TestHistogram, 5);
+
+        #[test]
+        fn value_at_quantile_excludes_underflow_from_the_bins() {
+            let mut h = TestHistogram::with_const_width_saturating(0., 10.);
+            h.add(-5.).unwrap();
+            h.add(1.).unwrap();
+            h.add(2.).unwrap();
+            h.add(3.).unwrap();
+            assert_eq!(h.underflow(), 1);
+            assert_eq!(h.total_count(), 4);
+            let median = h.median();
+            assert!(median > 1. && median < 3., "median = {}", median);
+        }
+
+        #[test]
+        fn saturating_histogram_counts_out_of_range_samples() {
+            let mut h = TestHistogram::with_const_width_saturating(0., 10.);
+            h.add(-1.).unwrap();
+            h.add(100.).unwrap();
+            assert_eq!(h.underflow(), 1);
+            assert_eq!(h.overflow(), 1);
+            assert_eq!(h.total_count(), 2);
+        }
+
+        #[test]
+        fn quantile_below_is_fraction_of_total_count() {
+            let mut h = TestHistogram::with_const_width(0., 10.);
+            for i in 0..10 {
+                h.add(i as f64).unwrap();
+            }
+            assert_eq!(h.quantile_below(5.), 0.5);
+        }
+
+        fn median_via_generic_histogram_bound<H: Histogram>(h: &H) -> f64 {
+            h.median()
+        }
+
+        #[test]
+        fn quantile_methods_are_reachable_through_the_histogram_trait() {
+            let mut h = TestHistogram::with_const_width(0., 10.);
+            for i in 0..10 {
+                h.add(i as f64).unwrap();
+            }
+            assert_eq!(median_via_generic_histogram_bound(&h), h.median());
+
+            let dyn_h: &dyn Histogram = &h;
+            assert_eq!(dyn_h.quantile_below(5.), 0.5);
+        }
+
+        #[test]
+        fn display_includes_summary_stats() {
+            let mut h = TestHistogram::with_const_width(0., 10.);
+            h.add(1.).unwrap();
+            h.add(2.).unwrap();
+            let text = format!("{}", h);
+            assert!(text.contains("total samples = 2"));
+            assert!(text.contains("mean"));
+        }
+    }
+
+    mod weighted {
+        use crate::HistogramWeighted;
+
+        define_histogram_weighted!(TestHistogramWeighted, 5);
+
+        #[test]
+        fn add_weighted_accumulates_fractional_counts() {
+            let mut h = TestHistogramWeighted::with_const_width(0., 10.);
+            h.add_weighted(1., 0.5).unwrap();
+            h.add_weighted(1., 0.25).unwrap();
+            assert_eq!(h.bins()[0], 0.75);
+        }
+
+        #[test]
+        fn value_at_quantile_excludes_underflow_from_the_bins() {
+            let mut h = TestHistogramWeighted::with_const_width_saturating(0., 10.);
+            h.add_weighted(-5., 1.).unwrap();
+            h.add_weighted(1., 1.).unwrap();
+            h.add_weighted(2., 1.).unwrap();
+            h.add_weighted(3., 1.).unwrap();
+            assert_eq!(h.underflow(), 1.);
+            assert_eq!(h.total_count(), 4.);
+            let median = h.median();
+            assert!(median > 1. && median < 3., "median = {}", median);
+        }
+
+        fn median_via_generic_histogram_weighted_bound<H: HistogramWeighted>(h: &H) -> f64 {
+            h.median()
+        }
+
+        #[test]
+        fn quantile_methods_are_reachable_through_the_histogram_weighted_trait() {
+            let mut h = TestHistogramWeighted::with_const_width(0., 10.);
+            for i in 0..10 {
+                h.add(i as f64).unwrap();
+            }
+            assert_eq!(median_via_generic_histogram_weighted_bound(&h), h.median());
+
+            let dyn_h: &dyn HistogramWeighted = &h;
+            assert_eq!(dyn_h.quantile_below(5.), 0.5);
+        }
+
+        #[test]
+        fn display_includes_summary_stats() {
+            let mut h = TestHistogramWeighted::with_const_width(0., 10.);
+            h.add_weighted(1., 0.5).unwrap();
+            h.add_weighted(2., 0.5).unwrap();
+            let text = format!("{}", h);
+            assert!(text.contains("total samples = 1"));
+            assert!(text.contains("mean"));
+        }
+    }
+}